@@ -1,5 +1,6 @@
 use std::env;
 use std::path::Path;
+use std::time::Instant;
 
 use sdl2::event::Event;
 use sdl2::pixels::Color;
@@ -10,10 +11,24 @@ use image::{ImageBuffer, Rgb};
 use opensimplex_noise_rs::OpenSimplexNoise;
 
 use rand::Rng;
+use rayon::prelude::*;
 
 const IMAGE_SIZE: [i32; 2] = [2048, 2048];
 const WIN_SIZE: [i32; 2] = [512, 512];
 
+// How strongly low-frequency noise perturbs the latitude-driven base temperature.
+const TEMPERATURE_NOISE_STRENGTH: f32 = 0.3;
+// How much each unit of height cools a pixel down. Snow is chosen by height alone (see
+// biome_table), so this only moves the temperature-bounded biomes (Tundra, Desert, Rainforest).
+const TEMPERATURE_LAPSE_RATE: f32 = 0.5;
+
+// Distance between adjacent height samples, used when turning the finite-difference
+// slope into a surface normal.
+const CELL_SIZE: f32 = 1.0;
+// Direction the hillshade "sun" comes from; doesn't need to be normalized, compute_shading
+// normalizes it before taking the dot product.
+const LIGHT_DIRECTION: (f32, f32, f32) = (-0.5, -0.5, 1.0);
+
 
 fn sum_octaves(
     num_iterations: i32,
@@ -46,115 +61,429 @@ fn color_to_array(color: Color) -> [u8; 3] {
 async fn generate_gradient() -> Vec<f32> {
     let mut gradient: Vec<f32> = vec![1.0; (IMAGE_SIZE[0] * IMAGE_SIZE[1]) as usize];
 
-    for x in 0..IMAGE_SIZE[0] {
-        for y in 0..IMAGE_SIZE[1] {
-            let mut color_value: f32;
-
-            let a = if x > (IMAGE_SIZE[0] / 2) {
-                IMAGE_SIZE[0] - x
-            } else {
-                x
-            };
-
-            let b = if y > IMAGE_SIZE[1] / 2 {
-                IMAGE_SIZE[1] - y
-            } else {
-                y
-            };
-
-            let smaller = std::cmp::min(a, b) as f32;
-            color_value = smaller / (IMAGE_SIZE[0] as f32 / 2.0);
-
-            color_value = 1.0 - color_value;
-            color_value = color_value * color_value;
-
-            gradient[get_id_from_pos(x, y)] = match color_value - 0.1 {
-                x if x > 1.0 => 1.0,
-                x if x < 0.0 => 0.0,
-                x => x,
-            };
-        }
-    }
+    gradient
+        .par_chunks_mut(IMAGE_SIZE[0] as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let y = y as i32;
+            for x in 0..IMAGE_SIZE[0] {
+                let a = if x > (IMAGE_SIZE[0] / 2) {
+                    IMAGE_SIZE[0] - x
+                } else {
+                    x
+                };
+
+                let b = if y > IMAGE_SIZE[1] / 2 {
+                    IMAGE_SIZE[1] - y
+                } else {
+                    y
+                };
+
+                let smaller = std::cmp::min(a, b) as f32;
+                let mut color_value = smaller / (IMAGE_SIZE[0] as f32 / 2.0);
+
+                color_value = 1.0 - color_value;
+                color_value = color_value * color_value;
+
+                row[x as usize] = match color_value - 0.1 {
+                    v if v > 1.0 => 1.0,
+                    v if v < 0.0 => 0.0,
+                    v => v,
+                };
+            }
+        });
 
     gradient
 }
 
-async fn generate_maps(gradient: &Vec<f32>) -> (Vec<f32>, Vec<f32>) {
+async fn generate_maps(gradient: &Vec<f32>) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
     let mut rng = rand::thread_rng();
 
-    let (mut height_map, mut biome_map) = futures::join!(
+    let (mut height_map, mut humidity_map, temperature_noise) = futures::join!(
         generate_noise_map(rng.gen_range(0, std::i64::MAX), 0.004),
-        generate_noise_map(rng.gen_range(0, std::i64::MAX), 0.007)
+        generate_noise_map(rng.gen_range(0, std::i64::MAX), 0.007),
+        generate_noise_map(rng.gen_range(0, std::i64::MAX), 0.0015)
     );
 
-    for x in 0..IMAGE_SIZE[0] {
-        for y in 0..IMAGE_SIZE[1] {
-            height_map[get_id_from_pos(x, y)] =
-                height_map[get_id_from_pos(x, y)] * 1.1 - gradient[get_id_from_pos(x, y)] * 0.8;
-            biome_map[get_id_from_pos(x, y)] =
-                biome_map[get_id_from_pos(x, y)] - (0.1 - gradient[get_id_from_pos(x, y)]) * 0.4;
-            if height_map[get_id_from_pos(x, y)] < 0.0 {
-                height_map[get_id_from_pos(x, y)] = 0.0;
+    let width = IMAGE_SIZE[0] as usize;
+    height_map
+        .par_chunks_mut(width)
+        .zip(humidity_map.par_chunks_mut(width))
+        .zip(gradient.par_chunks(width))
+        .for_each(|((height_row, humidity_row), gradient_row)| {
+            for x in 0..width {
+                height_row[x] = height_row[x] * 1.1 - gradient_row[x] * 0.8;
+                humidity_row[x] = humidity_row[x] - (0.1 - gradient_row[x]) * 0.4;
+                if height_row[x] < 0.0 {
+                    height_row[x] = 0.0;
+                }
+                if humidity_row[x] < 0.0 {
+                    humidity_row[x] = 0.0;
+                }
             }
-            if biome_map[get_id_from_pos(x, y)] < 0.0 {
-                biome_map[get_id_from_pos(x, y)] = 0.0;
+        });
+
+    let temperature_map = generate_temperature_map(&height_map, &temperature_noise);
+
+    (height_map, temperature_map, humidity_map)
+}
+
+// Poles are cold, the equator is warm; higher terrain cools further via a lapse-rate
+// term, and low-frequency noise is blended in so temperature bands aren't perfectly flat.
+fn generate_temperature_map(height_map: &Vec<f32>, noise: &Vec<f32>) -> Vec<f32> {
+    let mut temperature_map: Vec<f32> = vec![0.0; (IMAGE_SIZE[0] * IMAGE_SIZE[1]) as usize];
+    let width = IMAGE_SIZE[0] as usize;
+
+    temperature_map
+        .par_chunks_mut(width)
+        .zip(height_map.par_chunks(width))
+        .zip(noise.par_chunks(width))
+        .enumerate()
+        .for_each(|(y, ((temperature_row, height_row), noise_row))| {
+            let base_temp =
+                1.0 - (y as f32 - IMAGE_SIZE[1] as f32 / 2.0).abs() / (IMAGE_SIZE[1] as f32 / 2.0);
+
+            for x in 0..width {
+                let mut temp = base_temp - height_row[x] * TEMPERATURE_LAPSE_RATE;
+                temp += (noise_row[x] - 0.5) * TEMPERATURE_NOISE_STRENGTH;
+
+                temperature_row[x] = temp.max(0.0).min(1.0);
             }
-        }
-    }
+        });
+
+    temperature_map
+}
 
-    (height_map, biome_map)
+// Reads a `--threads N` pair off argv so users can cap how many cores rayon uses.
+fn parse_threads_arg() -> Option<usize> {
+    let args: Vec<String> = env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--threads")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|value| value.parse().ok())
 }
 
 fn get_id_from_pos(x: i32, y: i32) -> usize {
     (x + IMAGE_SIZE[0] * y) as usize
 }
 
+// Clamps to the image borders by reusing the edge sample instead of wrapping or panicking.
+fn sample_height(height_map: &Vec<f32>, x: i32, y: i32) -> f32 {
+    let x = x.max(0).min(IMAGE_SIZE[0] - 1);
+    let y = y.max(0).min(IMAGE_SIZE[1] - 1);
+    height_map[get_id_from_pos(x, y)]
+}
+
+fn normalize(v: (f32, f32, f32)) -> (f32, f32, f32) {
+    let length = (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt();
+    (v.0 / length, v.1 / length, v.2 / length)
+}
+
+// Finite-difference surface normal at (x, y), derived from the height field's local slope.
+fn compute_normal(height_map: &Vec<f32>, x: i32, y: i32) -> (f32, f32, f32) {
+    let dx = sample_height(height_map, x + 1, y) - sample_height(height_map, x - 1, y);
+    let dy = sample_height(height_map, x, y + 1) - sample_height(height_map, x, y - 1);
+
+    normalize((-dx, -dy, CELL_SIZE))
+}
+
+// Dots the surface normal with the light direction to get a [0, 1] shading factor.
+fn compute_shading(normal: (f32, f32, f32)) -> f32 {
+    let light = normalize(LIGHT_DIRECTION);
+    let dot = normal.0 * light.0 + normal.1 * light.1 + normal.2 * light.2;
+    dot.max(0.0).min(1.0)
+}
+
 async fn generate_noise_map(seed: i64, scale: f64) -> Vec<f32> {
     let noise_generator = OpenSimplexNoise::new(Some(seed));
 
     let mut map: Vec<f32> = vec![0.0; (IMAGE_SIZE[0] * IMAGE_SIZE[1]) as usize];
-    for x in 0..IMAGE_SIZE[0] {
-        for y in 0..IMAGE_SIZE[1] {
-            let val = sum_octaves(16, (x, y), 0.5, scale, 0.0, 1.0, |x, y| {
-                noise_generator.eval_2d(x, y)
-            });
+    map.par_chunks_mut(IMAGE_SIZE[0] as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for x in 0..IMAGE_SIZE[0] {
+                let val = sum_octaves(16, (x, y as i32), 0.5, scale, 0.0, 1.0, |x, y| {
+                    noise_generator.eval_2d(x, y)
+                });
+
+                row[x as usize] = val as f32;
+            }
+        });
+    map
+}
+
+// A Whittaker-style biome definition: a pixel belongs to the first entry
+// in the table whose height/temperature/humidity box contains it. Only Tundra, Desert
+// and Rainforest bound the temperature axis; the other entries leave it open (MIN/MAX).
+struct BiomeDef {
+    name: &'static str,
+    height_min: f32,
+    height_max: f32,
+    temp_min: f32,
+    temp_max: f32,
+    humidity_min: f32,
+    humidity_max: f32,
+    color: Color,
+}
+
+fn biome_table() -> Vec<BiomeDef> {
+    const MIN: f32 = f32::MIN;
+    const MAX: f32 = f32::MAX;
+
+    vec![
+        BiomeDef { name: "DeepWater", height_min: MIN, height_max: 0.39, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: MAX, color: Color::RGB(0, 62, 178) },
+        BiomeDef { name: "Water", height_min: 0.39, height_max: 0.42, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: MAX, color: Color::RGB(9, 82, 198) },
+        // Elevation-driven biomes are checked before Tundra so high peaks still render as
+        // Snow/HighMountain/Mountain in cold latitude bands instead of being swallowed by it.
+        BiomeDef { name: "Snow", height_min: 0.79, height_max: MAX, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: MAX, color: Color::RGB(235, 235, 235) },
+        BiomeDef { name: "HighMountain", height_min: 0.74, height_max: MAX, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: MAX, color: Color::RGB(160, 162, 143) },
+        BiomeDef { name: "Mountain", height_min: 0.68, height_max: MAX, temp_min: MIN, temp_max: MAX, humidity_min: 0.10, humidity_max: MAX, color: Color::RGB(140, 142, 123) },
+        BiomeDef { name: "Tundra", height_min: 0.42, height_max: 0.68, temp_min: MIN, temp_max: 0.2, humidity_min: MIN, humidity_max: MAX, color: Color::RGB(225, 225, 230) }, // cold regardless of elevation, below Mountain's threshold
+        BiomeDef { name: "Desert", height_min: 0.42, height_max: 0.62, temp_min: 0.7, temp_max: MAX, humidity_min: MIN, humidity_max: 0.35, color: Color::RGB(210, 180, 140) }, // hot + dry
+        BiomeDef { name: "Rainforest", height_min: 0.47, height_max: 0.62, temp_min: 0.6, temp_max: MAX, humidity_min: 0.6, humidity_max: MAX, color: Color::RGB(30, 110, 30) }, // hot + wet
+        BiomeDef { name: "Sand", height_min: 0.42, height_max: 0.46, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: 0.57, color: Color::RGB(194, 178, 128) },
+        BiomeDef { name: "WetSand", height_min: 0.42, height_max: 0.47, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: 0.6, color: Color::RGB(164, 148, 99) },
+        BiomeDef { name: "Dirt", height_min: 0.42, height_max: 0.47, temp_min: MIN, temp_max: MAX, humidity_min: 0.6, humidity_max: MAX, color: Color::RGB(114, 98, 49) },
+        BiomeDef { name: "Grass", height_min: 0.54, height_max: 0.62, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: 0.43, color: Color::RGB(120, 157, 80) },
+        BiomeDef { name: "HighDarkForest", height_min: MIN, height_max: 0.62, temp_min: MIN, temp_max: MAX, humidity_min: 0.58, humidity_max: MAX, color: Color::RGB(40, 77, 0) },
+        BiomeDef { name: "DarkForest", height_min: MIN, height_max: 0.62, temp_min: MIN, temp_max: MAX, humidity_min: 0.49, humidity_max: MAX, color: Color::RGB(60, 97, 20) },
+        BiomeDef { name: "LightForest", height_min: MIN, height_max: MAX, temp_min: MIN, temp_max: MAX, humidity_min: MIN, humidity_max: MAX, color: Color::RGB(85, 122, 45) }, // fallback
+    ]
+}
+
+fn classify_biome<'a>(height: f32, temperature: f32, humidity: f32, table: &'a [BiomeDef]) -> &'a BiomeDef {
+    table
+        .iter()
+        .find(|biome| {
+            height >= biome.height_min
+                && height < biome.height_max
+                && temperature >= biome.temp_min
+                && temperature < biome.temp_max
+                && humidity >= biome.humidity_min
+                && humidity < biome.humidity_max
+        })
+        .expect("biome_table must have a catch-all entry")
+}
+
+// How close a value needs to sit to one of a biome's box edges before the blend
+// pass starts mixing it with its neighbors.
+const BIOME_BLEND_MARGIN: f32 = 0.02;
+
+fn boundary_closeness(value: f32, min: f32, max: f32) -> f32 {
+    let dist_to_min = if min == f32::MIN { f32::MAX } else { (value - min).abs() };
+    let dist_to_max = if max == f32::MAX { f32::MAX } else { (value - max).abs() };
+    let dist = dist_to_min.min(dist_to_max);
+    (1.0 - (dist / BIOME_BLEND_MARGIN).min(1.0)).max(0.0)
+}
+
+// How strongly a pixel should blend with its neighbors: 0 deep inside a biome,
+// approaching 1 right at a height/temperature/humidity boundary.
+fn blend_weight(height: f32, temperature: f32, humidity: f32, biome: &BiomeDef) -> f32 {
+    let h = boundary_closeness(height, biome.height_min, biome.height_max);
+    let t = boundary_closeness(temperature, biome.temp_min, biome.temp_max);
+    let u = boundary_closeness(humidity, biome.humidity_min, biome.humidity_max);
+    h.max(t).max(u)
+}
+
+fn mix_colors(a: Color, b: Color, t: f32) -> Color {
+    Color::RGB(
+        (a.r as f32 * (1.0 - t) + b.r as f32 * t) as u8,
+        (a.g as f32 * (1.0 - t) + b.g as f32 * t) as u8,
+        (a.b as f32 * (1.0 - t) + b.b as f32 * t) as u8,
+    )
+}
+
+// Softens hard biome seams: pixels near a classification boundary are averaged with
+// their 4-neighborhood, weighted by how close they sit to that boundary. Pixels deep
+// inside a biome are left untouched.
+fn blend_biome_colors(
+    colors: &[Color],
+    height_map: &Vec<f32>,
+    temperature_map: &Vec<f32>,
+    humidity_map: &Vec<f32>,
+    biomes: &[BiomeDef],
+) -> Vec<Color> {
+    let mut blended = Vec::with_capacity(colors.len());
+
+    // Row-major order, so each push lands at get_id_from_pos(x, y).
+    for y in 0..IMAGE_SIZE[1] {
+        for x in 0..IMAGE_SIZE[0] {
+            let id = get_id_from_pos(x, y);
+            let biome = classify_biome(height_map[id], temperature_map[id], humidity_map[id], biomes);
+            let weight = blend_weight(height_map[id], temperature_map[id], humidity_map[id], biome);
+
+            if weight <= 0.0 {
+                blended.push(colors[id]);
+                continue;
+            }
+
+            let neighbors = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+            let (mut r, mut g, mut b, mut count) = (0u32, 0u32, 0u32, 0u32);
+            for (dx, dy) in neighbors {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && ny >= 0 && nx < IMAGE_SIZE[0] && ny < IMAGE_SIZE[1] {
+                    let neighbor_color = colors[get_id_from_pos(nx, ny)];
+                    r += neighbor_color.r as u32;
+                    g += neighbor_color.g as u32;
+                    b += neighbor_color.b as u32;
+                    count += 1;
+                }
+            }
+            let neighbor_avg = Color::RGB((r / count) as u8, (g / count) as u8, (b / count) as u8);
 
-            map[get_id_from_pos(x, y)] = val as f32;
+            blended.push(mix_colors(colors[id], neighbor_avg, weight));
         }
     }
-    map
+
+    blended
 }
 
-enum Biomes {
-    Grass,
-    DeepWater,
-    Water,
-    Dirt,
-    Sand,
-    WetSand,
-    DarkForest,
-    HighDarkForest,
-    LightForest,
-    Mountain,
-    HighMountain,
-    Snow,
+// A single scatterable feature (tree, jungle grass, rock, ...). `radius` is the
+// minimum spacing between accepted points of this type and doubles as its density knob.
+struct DecorationDef {
+    id: &'static str,
+    radius: f32,
+    allowed_biomes: &'static [&'static str],
+    marker_color: Color,
 }
 
-fn get_biome_color(biome: Biomes) -> Color {
-    match biome {
-        Biomes::Grass => Color::RGB(120, 157, 80),
-        Biomes::Water => Color::RGB(9, 82, 198),
-        Biomes::DeepWater => Color::RGB(0, 62, 178),
-        Biomes::Dirt => Color::RGB(114, 98, 49),
-        Biomes::Sand => Color::RGB(194, 178, 128),
-        Biomes::WetSand => Color::RGB(164, 148, 99),
-        Biomes::DarkForest => Color::RGB(60, 97, 20),
-        Biomes::HighDarkForest => Color::RGB(40, 77, 0),
-        Biomes::LightForest => Color::RGB(85, 122, 45),
-        Biomes::Mountain => Color::RGB(140, 142, 123),
-        Biomes::HighMountain => Color::RGB(160, 162, 143),
-        Biomes::Snow => Color::RGB(235, 235, 235),
+fn decoration_table() -> Vec<DecorationDef> {
+    vec![
+        DecorationDef {
+            id: "tree",
+            radius: 10.0,
+            allowed_biomes: &["DarkForest", "HighDarkForest", "LightForest"],
+            marker_color: Color::RGB(25, 55, 10),
+        },
+        DecorationDef {
+            id: "jungle_grass",
+            radius: 5.0,
+            allowed_biomes: &["Rainforest"],
+            marker_color: Color::RGB(110, 190, 50),
+        },
+        DecorationDef {
+            id: "rock",
+            radius: 20.0,
+            allowed_biomes: &["Mountain", "HighMountain"],
+            marker_color: Color::RGB(105, 105, 100),
+        },
+    ]
+}
+
+struct PlacedDecoration {
+    x: i32,
+    y: i32,
+    decoration_id: &'static str,
+}
+
+// Bridson's Poisson-disk sampling: keeps an active list and a background grid
+// of cell size r/sqrt(2) so neighbor lookups stay O(1), restricted to pixels
+// whose biome is in the decoration's allowed set.
+//
+// `matching_pixels` is every pixel on the map whose biome is already in
+// `decoration.allowed_biomes` (the caller scans `biome_names` once per decoration
+// type). Picking the seed from that list instead of retrying uniform-random global
+// points means a decoration restricted to a rare biome (e.g. a small Rainforest
+// pocket) still gets sampled instead of silently producing zero placements.
+fn poisson_disk_sample(
+    decoration: &DecorationDef,
+    biome_at: impl Fn(i32, i32) -> &'static str,
+    matching_pixels: &[(i32, i32)],
+    rng: &mut impl Rng,
+) -> Vec<(i32, i32)> {
+    const K: i32 = 30;
+
+    if matching_pixels.is_empty() {
+        return Vec::new();
+    }
+
+    let r = decoration.radius;
+    let cell_size = r / std::f32::consts::SQRT_2;
+    let grid_w = (IMAGE_SIZE[0] as f32 / cell_size).ceil() as i32;
+    let grid_h = (IMAGE_SIZE[1] as f32 / cell_size).ceil() as i32;
+    let mut grid: Vec<Option<(f32, f32)>> = vec![None; (grid_w * grid_h) as usize];
+
+    let cell_of = |x: f32, y: f32| -> (i32, i32) { ((x / cell_size) as i32, (y / cell_size) as i32) };
+
+    let fits = |x: f32, y: f32, grid: &Vec<Option<(f32, f32)>>| -> bool {
+        if x < 0.0 || y < 0.0 || x >= IMAGE_SIZE[0] as f32 || y >= IMAGE_SIZE[1] as f32 {
+            return false;
+        }
+        let (gx, gy) = cell_of(x, y);
+        for ny in (gy - 2).max(0)..=(gy + 2).min(grid_h - 1) {
+            for nx in (gx - 2).max(0)..=(gx + 2).min(grid_w - 1) {
+                if let Some((ox, oy)) = grid[(nx + grid_w * ny) as usize] {
+                    let dx = ox - x;
+                    let dy = oy - y;
+                    if (dx * dx + dy * dy).sqrt() < r {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    };
+
+    let mut points: Vec<(f32, f32)> = Vec::new();
+    let mut active: Vec<(f32, f32)> = Vec::new();
+
+    let (seed_x, seed_y) = matching_pixels[rng.gen_range(0, matching_pixels.len())];
+    let seed = (seed_x as f32, seed_y as f32);
+    let (gx, gy) = cell_of(seed.0, seed.1);
+    grid[(gx + grid_w * gy) as usize] = Some(seed);
+    points.push(seed);
+    active.push(seed);
+
+    while let Some(point) = active.pop() {
+        let mut still_active = false;
+        for _ in 0..K {
+            let angle = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+            let dist = rng.gen_range(r, 2.0 * r);
+            let candidate = (point.0 + angle.cos() * dist, point.1 + angle.sin() * dist);
+
+            if fits(candidate.0, candidate.1, &grid)
+                && decoration
+                    .allowed_biomes
+                    .contains(&biome_at(candidate.0 as i32, candidate.1 as i32))
+            {
+                let (gx, gy) = cell_of(candidate.0, candidate.1);
+                grid[(gx + grid_w * gy) as usize] = Some(candidate);
+                points.push(candidate);
+                active.push(candidate);
+                still_active = true;
+            }
+        }
+        if still_active {
+            active.push(point);
+        }
+    }
+
+    points.into_iter().map(|(x, y)| (x as i32, y as i32)).collect()
+}
+
+fn draw_marker(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, x: i32, y: i32, color: Color) {
+    for oy in -1..=1 {
+        for ox in -1..=1 {
+            let (px, py) = (x + ox, y + oy);
+            if px >= 0 && py >= 0 && px < IMAGE_SIZE[0] && py < IMAGE_SIZE[1] {
+                let pixel = image.get_pixel_mut(px as u32, py as u32);
+                *pixel = image::Rgb(color_to_array(color));
+            }
+        }
+    }
+}
+
+fn write_decorations_manifest(decorations: &[PlacedDecoration]) {
+    let mut json = String::from("[\n");
+    for (i, decoration) in decorations.iter().enumerate() {
+        json.push_str(&format!(
+            "  {{\"x\": {}, \"y\": {}, \"decoration_id\": \"{}\"}}",
+            decoration.x, decoration.y, decoration.decoration_id
+        ));
+        json.push_str(if i + 1 < decorations.len() { ",\n" } else { "\n" });
     }
+    json.push(']');
+    std::fs::write("decorations.json", json).expect("failed to write decorations manifest");
 }
 
 enum AppState {
@@ -165,15 +494,28 @@ enum AppState {
 
 #[tokio::main]
 async fn main() {
+    if let Some(threads) = parse_threads_arg() {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("failed to configure rayon thread pool");
+        println!("Using {} threads", threads);
+    }
+
     let mut app_state = AppState::Load;
 
     let mut image = ImageBuffer::<Rgb<u8>, Vec<u8>>::new(IMAGE_SIZE[0] as u32, IMAGE_SIZE[1] as u32);
 
     println!("Generating gradient...");
+    let start = Instant::now();
     let gradient = generate_gradient().await;
-    println!("DONE");
+    println!("DONE in {:.2?}", start.elapsed());
     let mut height_map: Vec<f32> = Vec::new();
-    let mut biome_map: Vec<f32> = Vec::new();
+    let mut temperature_map: Vec<f32> = Vec::new();
+    let mut humidity_map: Vec<f32> = Vec::new();
+    let biomes = biome_table();
+    // Toggled at runtime with the B key to compare crisp vs. blended biome edges.
+    let mut blend_enabled = true;
 
 
     'running: loop {
@@ -181,43 +523,98 @@ async fn main() {
             AppState::Load => {
 
                 println!("Generating maps...");
-                
-                let (height, biome) = generate_maps(&gradient).await;
+
+                let start = Instant::now();
+                let (height, temperature, humidity) = generate_maps(&gradient).await;
                 height_map = height;
-                biome_map = biome;
-                
-                println!("DONE");
-                
+                temperature_map = temperature;
+                humidity_map = humidity;
+
+                println!("DONE in {:.2?}", start.elapsed());
+
                 app_state = AppState::GenerateImage;
             }
             AppState::GenerateImage => {
                 println!("Generating image...");
+                let mut normal_image =
+                    ImageBuffer::<Rgb<u8>, Vec<u8>>::new(IMAGE_SIZE[0] as u32, IMAGE_SIZE[1] as u32);
+                let mut biome_names: Vec<&'static str> =
+                    vec!["LightForest"; (IMAGE_SIZE[0] * IMAGE_SIZE[1]) as usize];
+                let mut biome_colors: Vec<Color> =
+                    vec![Color::RGB(0, 0, 0); (IMAGE_SIZE[0] * IMAGE_SIZE[1]) as usize];
+
+                for x in 0..IMAGE_SIZE[0] {
+                    for y in 0..IMAGE_SIZE[1] {
+                        let id = get_id_from_pos(x, y);
+                        let height = height_map[id];
+                        let temperature = temperature_map[id];
+                        let humidity = humidity_map[id];
+
+                        let biome = classify_biome(height, temperature, humidity, &biomes);
+                        biome_names[id] = biome.name;
+                        biome_colors[id] = biome.color;
+                    }
+                }
+
+                let biome_colors = if blend_enabled {
+                    blend_biome_colors(&biome_colors, &height_map, &temperature_map, &humidity_map, &biomes)
+                } else {
+                    biome_colors
+                };
+
                 for x in 0..IMAGE_SIZE[0] {
                     for y in 0..IMAGE_SIZE[1] {
-                        let height = height_map[get_id_from_pos(x, y)];
-                        let moisture = biome_map[get_id_from_pos(x, y)];
-
-                        let biome = match (height, moisture) {
-                            (a, _) if a < 0.39 => Biomes::DeepWater,
-                            (a, _) if a < 0.42 => Biomes::Water,
-                            (a, b) if a < 0.46 && b < 0.57 => Biomes::Sand,
-                            (a, b) if a < 0.47 && b < 0.6 => Biomes::WetSand,
-                            (a, b) if a < 0.47 && b >= 0.6 => Biomes::Dirt,
-                            (a, b) if a > 0.54 && b < 0.43 && a < 0.62 => Biomes::Grass,
-                            (a, b) if a < 0.62 && b >= 0.58 => Biomes::HighDarkForest,
-                            (a, b) if a < 0.62 && b >= 0.49 => Biomes::DarkForest,
-                            (a, _) if a >= 0.79 => Biomes::Snow,
-                            (a, _) if a >= 0.74 => Biomes::HighMountain,
-                            (a, b) if a >= 0.68 && b >= 0.10 => Biomes::Mountain,
-                            _ => Biomes::LightForest,
-                        };
-
-                        let color = get_biome_color(biome);
+                        let id = get_id_from_pos(x, y);
+                        let normal = compute_normal(&height_map, x, y);
+                        let shading = compute_shading(normal);
+
+                        let color = biome_colors[id];
+                        let shaded = Color::RGB(
+                            (color.r as f32 * shading) as u8,
+                            (color.g as f32 * shading) as u8,
+                            (color.b as f32 * shading) as u8,
+                        );
                         let pixel = image.get_pixel_mut(x as u32, y as u32);
-                        *pixel = image::Rgb(color_to_array(color));
+                        *pixel = image::Rgb(color_to_array(shaded));
+
+                        let normal_pixel = normal_image.get_pixel_mut(x as u32, y as u32);
+                        *normal_pixel = image::Rgb([
+                            ((normal.0 * 0.5 + 0.5) * 255.0) as u8,
+                            ((normal.1 * 0.5 + 0.5) * 255.0) as u8,
+                            ((normal.2 * 0.5 + 0.5) * 255.0) as u8,
+                        ]);
+                    }
+                }
+
+                println!("Placing decorations...");
+                let mut rng = rand::thread_rng();
+                let mut placed_decorations: Vec<PlacedDecoration> = Vec::new();
+                for decoration in decoration_table() {
+                    let matching_pixels: Vec<(i32, i32)> = (0..IMAGE_SIZE[0])
+                        .flat_map(|x| (0..IMAGE_SIZE[1]).map(move |y| (x, y)))
+                        .filter(|&(x, y)| decoration.allowed_biomes.contains(&biome_names[get_id_from_pos(x, y)]))
+                        .collect();
+
+                    let points = poisson_disk_sample(
+                        &decoration,
+                        |x, y| biome_names[get_id_from_pos(x, y)],
+                        &matching_pixels,
+                        &mut rng,
+                    );
+                    for (x, y) in points {
+                        draw_marker(&mut image, x, y, decoration.marker_color);
+                        placed_decorations.push(PlacedDecoration {
+                            x,
+                            y,
+                            decoration_id: decoration.id,
+                        });
                     }
                 }
+                write_decorations_manifest(&placed_decorations);
+                println!("DONE");
+
                 image.save("output.png").unwrap();
+                normal_image.save("normals.png").unwrap();
                 println!("DONE");
                 app_state = AppState::View;
             },
@@ -263,6 +660,14 @@ async fn main() {
                                 app_state = AppState::Load;
                                 break 'view;
                             }
+                            Event::KeyDown {
+                                keycode: Some(Keycode::B),
+                                ..
+                            } => {
+                                blend_enabled = !blend_enabled;
+                                app_state = AppState::GenerateImage;
+                                break 'view;
+                            }
                             _ => {}
                         }
                     }